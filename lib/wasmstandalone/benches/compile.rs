@@ -0,0 +1,71 @@
+//! Benchmarks `compile_module` over a multi-hundred-function module, to quantify the effect of
+//! moving functions out of `TranslationResult` instead of cloning them and of emitting
+//! relocations into `Vec`s instead of `HashMap`s (see the `chunk0-7` change).
+
+#![feature(test)]
+
+extern crate cretonne;
+extern crate cton_frontend;
+extern crate cton_wasm;
+extern crate target_lexicon;
+extern crate test;
+extern crate wasmstandalone;
+
+use cretonne::ir::{AbiParam, ExternalName, Function, Signature};
+use cretonne::isa;
+use cretonne::settings::{self, Configurable};
+use cton_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cton_wasm::TranslationResult;
+use std::collections::HashMap;
+use std::str::FromStr;
+use target_lexicon::Triple;
+use test::Bencher;
+use wasmstandalone::execution::compile_module;
+use wasmstandalone::standalone::StandaloneRuntime;
+
+const FUNCTION_COUNT: usize = 300;
+
+/// Builds a module of `FUNCTION_COUNT` trivial, mutually non-calling functions (each one just
+/// returns a distinct `i32` constant), which is enough to make per-function allocation overhead
+/// in the compile loop show up in the timing without the benchmark being dominated by Cretonne's
+/// own codegen work for any single function.
+fn synthetic_module(isa: &isa::TargetIsa) -> TranslationResult {
+    let mut functions = Vec::with_capacity(FUNCTION_COUNT);
+    for i in 0..FUNCTION_COUNT {
+        let mut signature = Signature::new(isa.default_call_conv());
+        signature.returns.push(AbiParam::new(cretonne::ir::types::I32));
+        let mut func = Function::with_name_signature(ExternalName::user(0, i as u32), signature);
+        let mut fn_builder_ctx = FunctionBuilderContext::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_builder_ctx);
+            let block = builder.create_ebb();
+            builder.switch_to_block(block);
+            builder.seal_block(block);
+            let result = builder.ins().iconst(cretonne::ir::types::I32, i as i64);
+            builder.ins().return_(&[result]);
+            builder.finalize();
+        }
+        functions.push(func);
+    }
+    TranslationResult {
+        functions,
+        exports: HashMap::new(),
+        start_index: Some(0),
+        function_imports_count: 0,
+    }
+}
+
+#[bench]
+fn compile_module_multi_hundred_functions(b: &mut Bencher) {
+    let mut shared_builder = settings::builder();
+    shared_builder.enable("enable_verifier").unwrap();
+    shared_builder.set("is_64bit", "1").unwrap();
+    let isa_builder = isa::lookup(Triple::from_str("x86_64").unwrap()).unwrap();
+    let isa = isa_builder.finish(settings::Flags::new(&shared_builder));
+    let runtime = StandaloneRuntime::new();
+
+    b.iter(|| {
+        let mut trans_result = synthetic_module(&*isa);
+        compile_module(&mut trans_result, &*isa, &runtime, None).unwrap();
+    });
+}
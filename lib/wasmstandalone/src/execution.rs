@@ -6,65 +6,427 @@ use cretonne::verifier;
 use cretonne::settings::Configurable;
 use cretonne::result::CtonError;
 use cretonne::ir::entities::AnyEntity;
-use cretonne::ir::{Ebb, FuncRef, JumpTable, Function};
-use cretonne::binemit::{RelocSink, Reloc, CodeOffset};
+use cretonne::ir::{Ebb, FuncRef, JumpTable, Function, ExternalName, LibCall, Signature, AbiParam,
+                   Type, TrapCode, SourceLoc};
+use cretonne::ir::types;
+use cretonne::ir::MemFlags;
+use cretonne::binemit::{RelocSink, Reloc, CodeOffset, TrapSink};
 use cton_wasm::{TranslationResult, FunctionIndex, WasmRuntime};
-use std::mem::transmute;
+use cton_frontend::{FunctionBuilder, FunctionBuilderContext};
+use faerie::{Artifact, Decl, Link};
+use faerie::Reloc as ObjReloc;
+use std::mem::{self, transmute, size_of, zeroed};
 use region::Protection;
 use region::protect;
+use memmap::MmapMut;
 use std::collections::HashMap;
+use std::ptr;
 use std::ptr::write_unaligned;
-use std::fmt::Write;
+use std::fmt::{self, Write};
+use std::cell::{Cell, RefCell};
+use std::sync::Once;
 use standalone::StandaloneRuntime;
 
-type RelocRef = u16;
+// Raw ELF x86-64 relocation types, used when emitting jump-table entries as object-file
+// relocations against a function symbol (faerie's `Link` has no addend of its own).
+const R_X86_64_64: u32 = 1;
+const R_X86_64_PC32: u32 = 2;
 
-// Implementation of a relocation sink that just saves all the information for later
+/// The width, in bytes, of one jump-table entry for the given reloc kind: an absolute pointer
+/// for `Abs8`, or the 4-byte offset relative to the table's own base that x86's native
+/// `jump_table_entry` lowering reads back for everything else. This is only known once Cretonne
+/// has actually emitted the reloc, not ahead of time.
+fn jt_entry_size(reloc: Reloc) -> usize {
+    match reloc {
+        Reloc::Abs8 => size_of::<u64>(),
+        _ => size_of::<u32>(),
+    }
+}
+
+/// The byte offset, within a function's jump-table data area, of entry `index`'s slot, for a
+/// table whose entries are `entry_size` bytes wide (see `jt_entry_size`). Broken out on its own
+/// so the layout and patching code can't independently drift onto two different strides.
+fn jt_entry_offset(entry_size: usize, index: usize) -> usize {
+    index * entry_size
+}
+
+// Implementation of a relocation sink that just saves all the information for later, in the
+// order Cretonne emits it.
 struct StandaloneRelocSink {
-    ebbs: HashMap<RelocRef, (Ebb, CodeOffset)>,
-    funcs: HashMap<RelocRef, (FuncRef, CodeOffset)>,
-    jts: HashMap<RelocRef, (JumpTable, CodeOffset)>,
+    ebbs: Vec<(Ebb, CodeOffset)>,
+    funcs: Vec<(FuncRef, CodeOffset)>,
+    jts: Vec<(JumpTable, Reloc, CodeOffset)>,
+    externals: Vec<(ExternalName, Reloc, CodeOffset)>,
+}
+
+// A function that has been compiled and emitted to a scratch buffer, but not yet copied into
+// the final code region or relocated against it.
+struct PendingFunction {
+    code: Vec<u8>,
+    relocs: StandaloneRelocSink,
+    trap_sites: Vec<(CodeOffset, TrapCode)>,
+    il_func: Function,
 }
 
 // Contains all the metadata necessary to perform relocations
 struct FunctionMetaData {
     relocs: StandaloneRelocSink,
     il_func: Function,
+    // Byte offset, within the code region, of this function's jump-table data area (laid out
+    // right after its code), and the local offset of each of the function's jump tables within
+    // that area.
+    jt_base: usize,
+    jt_layout: HashMap<JumpTable, usize>,
 }
 
 impl RelocSink for StandaloneRelocSink {
-    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb: Ebb) {
-        self.ebbs.insert(reloc.0, (ebb, offset));
+    fn reloc_ebb(&mut self, offset: CodeOffset, _reloc: Reloc, ebb: Ebb) {
+        self.ebbs.push((ebb, offset));
     }
-    fn reloc_func(&mut self, offset: CodeOffset, reloc: Reloc, func: FuncRef) {
-        self.funcs.insert(reloc.0, (func, offset));
+    fn reloc_func(&mut self, offset: CodeOffset, _reloc: Reloc, func: FuncRef) {
+        self.funcs.push((func, offset));
     }
     fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
-        self.jts.insert(reloc.0, (jt, offset));
+        self.jts.push((jt, reloc, offset));
+    }
+    fn reloc_external(&mut self, offset: CodeOffset, reloc: Reloc, name: &ExternalName) {
+        self.externals.push((name.clone(), reloc, offset));
     }
 }
 
 impl StandaloneRelocSink {
     fn new() -> StandaloneRelocSink {
         StandaloneRelocSink {
-            ebbs: HashMap::new(),
-            funcs: HashMap::new(),
-            jts: HashMap::new(),
+            ebbs: Vec::new(),
+            funcs: Vec::new(),
+            jts: Vec::new(),
+            externals: Vec::new(),
+        }
+    }
+}
+
+// Implementation of a trap sink that records the offset and kind of every trap site Cretonne
+// emits for a function (bounds checks, `trap`/`trapif`, etc.), so a later signal can be mapped
+// back to a `TrapCode`.
+struct StandaloneTrapSink {
+    sites: Vec<(CodeOffset, TrapCode)>,
+}
+
+impl StandaloneTrapSink {
+    fn new() -> StandaloneTrapSink {
+        StandaloneTrapSink { sites: Vec::new() }
+    }
+}
+
+impl TrapSink for StandaloneTrapSink {
+    fn trap(&mut self, offset: CodeOffset, _srcloc: SourceLoc, code: TrapCode) {
+        self.sites.push((offset, code));
+    }
+}
+
+/// A WebAssembly trap caught from generated code and safely unwound out of, instead of crashing
+/// the process.
+#[derive(Debug, Clone, Copy)]
+pub struct Trap {
+    /// The kind of trap that was hit: an explicit `trap`/`trapif`, or a bounds check inserted by
+    /// `StandaloneRuntime` for a heap or table access.
+    pub code: TrapCode,
+    /// The offset of the faulting instruction from the start of the whole code region (there is
+    /// no per-function breakdown: trap sites are recorded against the region as a whole).
+    pub code_offset: usize,
+}
+
+/// An error produced while executing compiled code: either a setup/argument error, or a
+/// WebAssembly trap caught from generated code.
+#[derive(Debug)]
+pub enum ExecutionError {
+    Trap(Trap),
+    Message(String),
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExecutionError::Trap(ref trap) => {
+                write!(f, "wasm trap: {} at offset {}", trap.code, trap.code_offset)
+            }
+            ExecutionError::Message(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<String> for ExecutionError {
+    fn from(msg: String) -> ExecutionError {
+        ExecutionError::Message(msg)
+    }
+}
+
+// The signal handler below communicates with `guarded_call` purely through thread-local state:
+// the code range currently being executed (so we can tell a trap in our own JIT code apart from
+// an unrelated crash), the trap-site table for that code, the buffer `sigsetjmp`/`siglongjmp`
+// use to unwind, and the trap that was found at the faulting PC.
+thread_local! {
+    static CODE_RANGE: Cell<(usize, usize)> = Cell::new((0, 0));
+    static TRAP_SITES: RefCell<Vec<(usize, TrapCode)>> = RefCell::new(Vec::new());
+    static JMP_BUF: RefCell<SigJmpBuf> = RefCell::new(SigJmpBuf([0; 32]));
+    static CAUGHT_TRAP: Cell<Option<(TrapCode, usize)>> = Cell::new(None);
+}
+
+// Opaque, over-sized storage for a `sigjmp_buf`: only `sigsetjmp`/`siglongjmp` ever read or
+// write its contents.
+#[repr(C)]
+struct SigJmpBuf([u64; 32]);
+
+extern "C" {
+    fn sigsetjmp(env: *mut SigJmpBuf, savemask: libc::c_int) -> libc::c_int;
+    fn siglongjmp(env: *mut SigJmpBuf, val: libc::c_int) -> !;
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+unsafe fn faulting_pc(context: *mut libc::c_void) -> usize {
+    let ucontext = context as *mut libc::ucontext_t;
+    (*ucontext).uc_mcontext.gregs[libc::REG_RIP as usize] as usize
+}
+
+extern "C" fn handle_trap_signal(
+    signum: libc::c_int,
+    _siginfo: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    unsafe {
+        let pc = faulting_pc(context);
+        let (code_start, code_end) = CODE_RANGE.with(|range| range.get());
+        let trap = if pc >= code_start && pc < code_end {
+            let code_offset = pc - code_start;
+            // Cretonne records one site per exact faulting PC; a nearest-site-below match would
+            // reclassify any unrelated crash landing after some recorded site - including one in
+            // a different, later function, or in the jump-table data interleaved into the same
+            // region - as that site's trap, silently turning a real bug into a recoverable `Err`.
+            TRAP_SITES.with(|sites| {
+                sites
+                    .borrow()
+                    .iter()
+                    .find(|&&(offset, _)| offset == code_offset)
+                    .map(|&(_, code)| (code, code_offset))
+            })
+        } else {
+            None
+        };
+        match trap {
+            Some(trap) => {
+                CAUGHT_TRAP.with(|caught| caught.set(Some(trap)));
+                JMP_BUF.with(|buf| siglongjmp(buf.borrow_mut().0.as_mut_ptr() as *mut SigJmpBuf, 1));
+            }
+            // Not one of ours (or we can't place it): restore the default behaviour and let the
+            // process die the way it would have without us installed.
+            None => {
+                libc::signal(signum, libc::SIG_DFL);
+            }
+        }
+    }
+}
+
+fn install_trap_handlers() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| unsafe {
+        for &signum in &[libc::SIGSEGV, libc::SIGILL, libc::SIGFPE] {
+            let mut sa: libc::sigaction = zeroed();
+            sa.sa_sigaction = handle_trap_signal as usize;
+            sa.sa_flags = libc::SA_SIGINFO;
+            libc::sigemptyset(&mut sa.sa_mask);
+            libc::sigaction(signum, &sa, ptr::null_mut());
+        }
+    });
+}
+
+/// Runs `body`, which is expected to call into generated code, with the trap-catching machinery
+/// armed. If a SIGSEGV/SIGILL/SIGFPE lands at an address this module's `trap_sites` recognizes,
+/// unwinds safely out of `body` and returns `Err(ExecutionError::Trap(..))` instead of crashing.
+unsafe fn guarded_call<F, R>(
+    code: &CodeMemory,
+    trap_sites: &[(usize, TrapCode)],
+    body: F,
+) -> Result<R, ExecutionError>
+where
+    F: FnOnce() -> R,
+{
+    install_trap_handlers();
+    let code_start = code.as_ptr() as usize;
+    CODE_RANGE.with(|range| range.set((code_start, code_start + code.len())));
+    TRAP_SITES.with(|sites| *sites.borrow_mut() = trap_sites.to_vec());
+    let jumped = JMP_BUF.with(|buf| {
+        sigsetjmp(buf.borrow_mut().0.as_mut_ptr() as *mut SigJmpBuf, 1)
+    });
+    if jumped != 0 {
+        let (code, code_offset) = CAUGHT_TRAP.with(|caught| caught.take()).expect(
+            "siglongjmp into guarded_call without a recorded trap",
+        );
+        return Err(ExecutionError::Trap(Trap { code, code_offset }));
+    }
+    Ok(body())
+}
+
+/// Resolves the host address backing a named external symbol: either a WebAssembly import
+/// (delegated to the user-supplied closure) or one of Cretonne's runtime libcalls (resolved
+/// against the built-in table below).
+pub trait SymbolResolver {
+    /// Returns the absolute address `name` refers to, or `None` if it is not a recognized import.
+    fn resolve_symbol(&self, name: &ExternalName) -> Option<*const u8>;
+}
+
+impl<F> SymbolResolver for F
+where
+    F: Fn(&ExternalName) -> Option<*const u8>,
+{
+    fn resolve_symbol(&self, name: &ExternalName) -> Option<*const u8> {
+        (self)(name)
+    }
+}
+
+// Declarations for the libm entry points backing Cretonne's float-rounding libcalls, and for
+// the C library routines backing its bulk-memory libcalls.
+extern "C" {
+    fn ceilf(x: f32) -> f32;
+    fn floorf(x: f32) -> f32;
+    fn truncf(x: f32) -> f32;
+    fn nearbyintf(x: f32) -> f32;
+    fn ceil(x: f64) -> f64;
+    fn floor(x: f64) -> f64;
+    fn trunc(x: f64) -> f64;
+    fn nearbyint(x: f64) -> f64;
+    fn memcpy(dest: *mut u8, src: *const u8, count: usize) -> *mut u8;
+    fn memmove(dest: *mut u8, src: *const u8, count: usize) -> *mut u8;
+    fn memset(dest: *mut u8, val: i32, count: usize) -> *mut u8;
+}
+
+/// Maps one of Cretonne's built-in libcalls to the address of its host implementation.
+fn libcall_address(call: LibCall) -> *const u8 {
+    match call {
+        LibCall::CeilF32 => ceilf as *const u8,
+        LibCall::CeilF64 => ceil as *const u8,
+        LibCall::FloorF32 => floorf as *const u8,
+        LibCall::FloorF64 => floor as *const u8,
+        LibCall::TruncF32 => truncf as *const u8,
+        LibCall::TruncF64 => trunc as *const u8,
+        LibCall::NearestF32 => nearbyintf as *const u8,
+        LibCall::NearestF64 => nearbyint as *const u8,
+        LibCall::Memcpy => memcpy as *const u8,
+        LibCall::Memmove => memmove as *const u8,
+        LibCall::Memset => memset as *const u8,
+        other => panic!("no host implementation registered for libcall {}", other),
+    }
+}
+
+/// Resolves an `ExternalName` to an absolute host address, trying the built-in libcall table
+/// first and falling back to the user-supplied `resolver` for WebAssembly imports.
+fn resolve_external(
+    name: &ExternalName,
+    resolver: Option<&SymbolResolver>,
+) -> Option<*const u8> {
+    match *name {
+        ExternalName::LibCall(call) => Some(libcall_address(call)),
+        ref other => resolver.and_then(|resolver| resolver.resolve_symbol(other)),
+    }
+}
+
+/// A single, page-aligned region of memory holding the compiled code of every function in a
+/// module, back to back. The region is `ReadWrite` while functions are emitted and relocated
+/// into it, and is flipped to `ReadExecute` exactly once by `finalize`, so it is never both
+/// writable and executable at the same time (W^X).
+struct CodeMemory {
+    map: MmapMut,
+}
+
+impl CodeMemory {
+    /// Allocates a fresh, writable region large enough to hold `size` bytes of code.
+    fn with_size(size: usize) -> Result<CodeMemory, String> {
+        MmapMut::map_anon(size)
+            .map(|map| CodeMemory { map })
+            .map_err(|err| format!("failed to allocate code memory: {}", err))
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.map.as_mut_ptr()
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        self.map.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Flips the whole region from `ReadWrite` to `ReadExecute`. Must only be called once all
+    /// functions have been emitted and relocated.
+    fn finalize(&mut self) -> Result<(), String> {
+        unsafe {
+            protect(self.map.as_ptr(), self.map.len(), Protection::ReadExecute).map_err(|err| {
+                format!("failed to make code memory executable: {}", err.description())
+            })
         }
     }
 }
 
 /// Structure containing the compiled code of the functions, ready to be executed.
 pub struct ExecutableCode {
-    functions_code: Vec<Vec<u8>>,
+    code: CodeMemory,
+    offsets: Vec<usize>,
+    signatures: Vec<Signature>,
+    exports: HashMap<String, FunctionIndex>,
     start_index: FunctionIndex,
+    trap_sites: Vec<(usize, TrapCode)>,
+}
+
+/// A typed WebAssembly value, passed to and returned from exported functions invoked through
+/// `ExecutableCode::invoke`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Val {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+impl Val {
+    fn ty(&self) -> Type {
+        match *self {
+            Val::I32(_) => types::I32,
+            Val::I64(_) => types::I64,
+            Val::F32(_) => types::F32,
+            Val::F64(_) => types::F64,
+        }
+    }
+
+    /// Encodes this value into the low bytes of a 64-bit word, for passing through the generic
+    /// memory-backed trampoline.
+    fn to_bits(&self) -> u64 {
+        match *self {
+            Val::I32(v) => v as u32 as u64,
+            Val::I64(v) => v as u64,
+            Val::F32(v) => v.to_bits() as u64,
+            Val::F64(v) => v.to_bits(),
+        }
+    }
+
+    /// Decodes a value of type `ty` out of the low bytes of a 64-bit word.
+    fn from_bits(bits: u64, ty: Type) -> Result<Val, String> {
+        match ty {
+            types::I32 => Ok(Val::I32(bits as i32)),
+            types::I64 => Ok(Val::I64(bits as i64)),
+            types::F32 => Ok(Val::F32(f32::from_bits(bits as u32))),
+            types::F64 => Ok(Val::F64(f64::from_bits(bits))),
+            other => Err(format!("unsupported value type: {}", other)),
+        }
+    }
 }
 
 /// Executes a module that has been translated with the `StandaloneRuntime` runtime implementation.
 pub fn compile_module(
-    trans_result: &TranslationResult,
+    trans_result: &mut TranslationResult,
     isa: &TargetIsa,
     runtime: &StandaloneRuntime,
+    symbol_resolver: Option<&SymbolResolver>,
 ) -> Result<ExecutableCode, String> {
     debug_assert!(
         trans_result.start_index.is_none() ||
@@ -79,72 +441,447 @@ pub fn compile_module(
     shared_builder.set("is_64bit", "1").expect(
         "Missing 64bits setting",
     );
-    let mut functions_metatada = Vec::new();
-    let mut functions_code = Vec::new();
-    for (function_index, function) in trans_result.functions.iter().enumerate() {
+    // Take the functions out of the translation result instead of cloning them one at a time:
+    // nothing below needs `trans_result.functions` again once compilation starts.
+    let functions = mem::replace(&mut trans_result.functions, Vec::new());
+    // First phase: compile and emit every function into a scratch buffer. This is the only way
+    // to learn both its exact code size and the true `Reloc` kind of each jump-table reference
+    // (Cretonne only picks an encoding once it emits), which is needed to lay each function's
+    // jump-table data out at a correctly-sized offset right after its code, before the
+    // functions are laid out at fixed offsets into what will become a single contiguous code
+    // region. The scratch code bytes themselves don't depend on the final address and are
+    // copied into the real region as-is in the second phase.
+    let mut pending = Vec::with_capacity(functions.len());
+    let mut offsets = Vec::with_capacity(functions.len());
+    let mut jt_bases = Vec::with_capacity(functions.len());
+    let mut jt_layouts = Vec::with_capacity(functions.len());
+    let mut signatures = Vec::with_capacity(functions.len());
+    let mut total_size = 0usize;
+    for function in functions.into_iter() {
         let mut context = Context::new();
-        verify_function(function, isa).unwrap();
-        context.func = function.clone(); // TODO: Avoid this clone.
+        verify_function(&function, isa).unwrap();
+        signatures.push(function.signature.clone());
+        context.func = function;
         let code_size = context.compile(isa).map_err(|e| {
             pretty_error(&context.func, Some(isa), e)
         })? as usize;
         if code_size == 0 {
             return Err(String::from("no code generated by Cretonne"));
         }
-        let mut code_buf: Vec<u8> = Vec::with_capacity(code_size);
-        code_buf.resize(code_size, 0);
+        let mut code = vec![0u8; code_size];
         let mut relocsink = StandaloneRelocSink::new();
-        context.emit_to_memory(code_buf.as_mut_ptr(), &mut relocsink, isa);
-        functions_metatada.push(FunctionMetaData {
+        let mut trapsink = StandaloneTrapSink::new();
+        context.emit_to_memory(code.as_mut_ptr(), &mut relocsink, &mut trapsink, isa);
+        let mut jt_layout = HashMap::new();
+        let mut jt_size = 0usize;
+        for &(jt, reloc, _) in &relocsink.jts {
+            jt_layout.insert(jt, jt_size);
+            jt_size += context.func.jump_tables[jt].len() * jt_entry_size(reloc);
+        }
+        offsets.push(total_size);
+        jt_bases.push(total_size + code_size);
+        jt_layouts.push(jt_layout);
+        total_size += code_size + jt_size;
+        pending.push(PendingFunction {
+            code,
             relocs: relocsink,
+            trap_sites: trapsink.sites,
             il_func: context.func,
         });
-        functions_code.push(code_buf);
+    }
+
+    // Second phase: allocate the region while it is still writable, and copy each function's
+    // already-emitted code to its recorded offset.
+    let mut code_memory = CodeMemory::with_size(total_size)?;
+    let mut functions_metatada = Vec::with_capacity(pending.len());
+    let mut trap_sites = Vec::new();
+    let per_function = pending.into_iter().zip(offsets.iter()).zip(
+        jt_bases.into_iter().zip(
+            jt_layouts.into_iter(),
+        ),
+    );
+    for ((pending_fn, &offset), (jt_base, jt_layout)) in per_function {
+        let PendingFunction {
+            code,
+            relocs,
+            trap_sites: fn_trap_sites,
+            il_func,
+        } = pending_fn;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                code.as_ptr(),
+                code_memory.as_mut_ptr().offset(offset as isize),
+                code.len(),
+            );
+        }
+        trap_sites.extend(fn_trap_sites.into_iter().map(
+            |(site_offset, code)| (offset + site_offset as usize, code),
+        ));
+        functions_metatada.push(FunctionMetaData {
+            relocs,
+            il_func,
+            jt_base,
+            jt_layout,
+        });
     }
     relocate(
         trans_result.function_imports_count,
         &functions_metatada,
-        &mut functions_code,
+        code_memory.as_mut_ptr(),
+        &offsets,
         runtime,
-    );
-    // After having emmitted the code to memory, we deal with relocations
+        symbol_resolver,
+    )?;
+    // All relocations have been applied: flip the region to executable exactly once.
+    code_memory.finalize()?;
     match trans_result.start_index {
         None => Err(String::from(
             "No start function defined, aborting execution",
         )),
         Some(index) => {
             Ok(ExecutableCode {
-                functions_code,
+                code: code_memory,
+                offsets,
+                signatures,
+                exports: trans_result.exports.clone(),
                 start_index: index,
+                trap_sites,
             })
         }
     }
 }
 
 /// Jumps to the code region of memory and execute the start function of the module.
-pub fn execute(exec: &ExecutableCode) -> Result<(), String> {
-    let code_buf = &exec.functions_code[exec.start_index];
+pub fn execute(exec: &ExecutableCode) -> Result<(), ExecutionError> {
+    let start_offset = exec.offsets[exec.start_index];
     unsafe {
-        match protect(
-            code_buf.as_ptr(),
-            code_buf.len(),
-            Protection::ReadWriteExecute,
-        ) {
-            Ok(()) => (),
-            Err(err) => {
-                return Err(format!(
-                    "failed to give executable permission to code: {}",
-                    err.description()
-                ))
-            }
-        };
         // Rather than writing inline assembly to jump to the code region, we use the fact that
         // the Rust ABI for calling a function with no arguments and no return matches the one of
         // the generated code.Thanks to this, we can transmute the code region into a first-class
         // Rust function and call it.
-        let start_func = transmute::<_, fn()>(code_buf.as_ptr());
-        start_func();
-        Ok(())
+        let start_func = transmute::<_, fn()>(exec.code.as_ptr().offset(start_offset as isize));
+        guarded_call(&exec.code, &exec.trap_sites, || start_func())
+    }
+}
+
+impl ExecutableCode {
+    /// Invokes the exported function named `name` with `args`, returning its results.
+    ///
+    /// `isa` must be the same target that the module was compiled for: it is needed to build a
+    /// marshaling trampoline on demand for signatures that fall outside the common arities.
+    ///
+    /// Returns an error if there is no such export, or if `args` doesn't match its signature.
+    pub fn invoke(
+        &self,
+        isa: &TargetIsa,
+        name: &str,
+        args: &[Val],
+    ) -> Result<Vec<Val>, ExecutionError> {
+        let index = *self.exports.get(name).ok_or_else(
+            || format!("no export named `{}`", name),
+        )?;
+        let signature = &self.signatures[index];
+        if signature.params.len() != args.len() ||
+            signature.params.iter().zip(args).any(|(param, arg)| {
+                param.value_type != arg.ty()
+            })
+        {
+            return Err(ExecutionError::Message(format!(
+                "argument mismatch calling `{}`: expected {:?}, got {:?}",
+                name,
+                signature.params.iter().map(|p| p.value_type).collect::<Vec<_>>(),
+                args
+            )));
+        }
+        let result = unsafe {
+            let entry = self.code.as_ptr().offset(self.offsets[index] as isize);
+            guarded_call(&self.code, &self.trap_sites, || {
+                call_entry(isa, entry, signature, args)
+            })?
+        };
+        result.map_err(ExecutionError::Message)
+    }
+}
+
+/// Dispatches a call to `entry` per `signature`, going through a direct `transmute` for the
+/// arities that show up for the vast majority of exports, and falling back to a Cretonne-built
+/// trampoline otherwise.
+unsafe fn call_entry(
+    isa: &TargetIsa,
+    entry: *const u8,
+    signature: &Signature,
+    args: &[Val],
+) -> Result<Vec<Val>, String> {
+    let returns: Vec<Type> = signature.returns.iter().map(|r| r.value_type).collect();
+    match (args.len(), returns.len()) {
+        (0, 0) => {
+            transmute::<_, fn()>(entry)();
+            Ok(vec![])
+        }
+        (0, 1) => {
+            Ok(vec![match returns[0] {
+                types::I32 => Val::I32(transmute::<_, fn() -> i32>(entry)()),
+                types::I64 => Val::I64(transmute::<_, fn() -> i64>(entry)()),
+                types::F32 => Val::F32(transmute::<_, fn() -> f32>(entry)()),
+                types::F64 => Val::F64(transmute::<_, fn() -> f64>(entry)()),
+                other => return Err(format!("unsupported return type: {}", other)),
+            }])
+        }
+        (1, 0) => {
+            match args[0] {
+                Val::I32(a) => transmute::<_, fn(i32)>(entry)(a),
+                Val::I64(a) => transmute::<_, fn(i64)>(entry)(a),
+                Val::F32(a) => transmute::<_, fn(f32)>(entry)(a),
+                Val::F64(a) => transmute::<_, fn(f64)>(entry)(a),
+            };
+            Ok(vec![])
+        }
+        (1, 1) => {
+            let result = match (args[0], returns[0]) {
+                (Val::I32(a), types::I32) => Val::I32(transmute::<_, fn(i32) -> i32>(entry)(a)),
+                (Val::I64(a), types::I64) => Val::I64(transmute::<_, fn(i64) -> i64>(entry)(a)),
+                (Val::F32(a), types::F32) => Val::F32(transmute::<_, fn(f32) -> f32>(entry)(a)),
+                (Val::F64(a), types::F64) => Val::F64(transmute::<_, fn(f64) -> f64>(entry)(a)),
+                _ => return call_via_trampoline(isa, entry, signature, args),
+            };
+            Ok(vec![result])
+        }
+        _ => call_via_trampoline(isa, entry, signature, args),
+    }
+}
+
+/// Builds, compiles and runs a one-off trampoline for a signature that direct `transmute` can't
+/// express: it loads each argument out of a `&[Val]`-backed buffer into the right register per
+/// the target ABI, calls `entry` indirectly, and marshals the results back into a `Vec<Val>`.
+unsafe fn call_via_trampoline(
+    isa: &TargetIsa,
+    entry: *const u8,
+    signature: &Signature,
+    args: &[Val],
+) -> Result<Vec<Val>, String> {
+    let pointer_type = isa.pointer_type();
+    let mut trampoline_sig = Signature::new(isa.default_call_conv());
+    trampoline_sig.params.push(AbiParam::new(pointer_type)); // entry
+    trampoline_sig.params.push(AbiParam::new(pointer_type)); // args_ptr
+    trampoline_sig.params.push(AbiParam::new(pointer_type)); // results_ptr
+
+    let mut func = Function::with_name_signature(ExternalName::user(0, 0), trampoline_sig);
+    let mut fn_builder_ctx = FunctionBuilderContext::new();
+    {
+        let mut builder = FunctionBuilder::new(&mut func, &mut fn_builder_ctx);
+        let block = builder.create_ebb();
+        builder.append_ebb_params_for_function_params(block);
+        builder.switch_to_block(block);
+        builder.seal_block(block);
+
+        let ebb_params = builder.ebb_params(block).to_vec();
+        let (entry_val, args_ptr, results_ptr) = (ebb_params[0], ebb_params[1], ebb_params[2]);
+
+        let mut callee_sig = Signature::new(isa.default_call_conv());
+        callee_sig.params.extend(signature.params.iter().cloned());
+        callee_sig.returns.extend(signature.returns.iter().cloned());
+        let sig_ref = builder.import_signature(callee_sig);
+
+        let mut call_args = Vec::with_capacity(signature.params.len());
+        for (i, param) in signature.params.iter().enumerate() {
+            let offset = (i * size_of::<u64>()) as i32;
+            call_args.push(builder.ins().load(
+                param.value_type,
+                MemFlags::new(),
+                args_ptr,
+                offset,
+            ));
+        }
+        let call = builder.ins().call_indirect(sig_ref, entry_val, &call_args);
+        let results = builder.inst_results(call).to_vec();
+        for (i, result) in results.iter().enumerate() {
+            let offset = (i * size_of::<u64>()) as i32;
+            builder.ins().store(MemFlags::new(), *result, results_ptr, offset);
+        }
+        builder.ins().return_(&[]);
+        builder.finalize();
+    }
+
+    let mut context = Context::for_function(func);
+    verify_function(&context.func, isa).map_err(|e| {
+        pretty_verifier_error(&context.func, Some(isa), &e)
+    })?;
+    let code_size = context.compile(isa).map_err(|e| {
+        pretty_error(&context.func, Some(isa), e)
+    })? as usize;
+    let mut trampoline_code = CodeMemory::with_size(code_size)?;
+    let mut relocsink = StandaloneRelocSink::new();
+    let mut trapsink = StandaloneTrapSink::new();
+    context.emit_to_memory(trampoline_code.as_mut_ptr(), &mut relocsink, &mut trapsink, isa);
+    trampoline_code.finalize()?;
+
+    let mut arg_buf: Vec<u64> = args.iter().map(Val::to_bits).collect();
+    let mut result_buf: Vec<u64> = vec![0; signature.returns.len()];
+    let trampoline = transmute::<_, fn(*const u8, *mut u64, *mut u64)>(trampoline_code.as_ptr());
+    trampoline(entry, arg_buf.as_mut_ptr(), result_buf.as_mut_ptr());
+
+    signature
+        .returns
+        .iter()
+        .zip(result_buf.iter())
+        .map(|(ret, &bits)| Val::from_bits(bits, ret.value_type))
+        .collect()
+}
+
+/// Returns the symbol name an `ExternalName` should be declared and linked against in an object
+/// file: Cretonne's libcalls and the translator's named host imports both already implement
+/// `Display` with a suitable symbol-like name.
+fn external_symbol_name(name: &ExternalName) -> String {
+    match *name {
+        ExternalName::LibCall(call) => call.to_string(),
+        ref other => other.to_string(),
+    }
+}
+
+/// Serializes a translated module into a relocatable object file instead of loading it into an
+/// in-process JIT, following the same path the Cranelift object backend takes for
+/// `rustc_codegen_cranelift`: each function's body goes into `.text`, jump-table payloads into
+/// `.rodata`, and Cretonne's recorded relocations become native object relocations against one
+/// symbol per wasm function (named by its export name, or by index when not exported).
+pub fn emit_object(
+    trans_result: &TranslationResult,
+    isa: &TargetIsa,
+    runtime: &StandaloneRuntime,
+    module_name: &str,
+) -> Result<Vec<u8>, String> {
+    let func_name = |index: FunctionIndex| -> String {
+        trans_result
+            .exports
+            .iter()
+            .find(|&(_, &export_index)| export_index == index)
+            .map(|(name, _)| name.clone())
+            .unwrap_or_else(|| format!("wasm_function_{}", index))
+    };
+
+    let mut obj = Artifact::new(isa.triple().clone(), module_name.to_string());
+    let mut compiled = Vec::with_capacity(trans_result.functions.len());
+    for (function_index, function) in trans_result.functions.iter().enumerate() {
+        let mut context = Context::new();
+        verify_function(function, isa).unwrap();
+        // Unlike `compile_module`, this function only borrows `trans_result`, so there's nothing
+        // to move the function out of; it has to be cloned.
+        context.func = function.clone();
+        let code_size = context.compile(isa).map_err(|e| {
+            pretty_error(&context.func, Some(isa), e)
+        })? as usize;
+        if code_size == 0 {
+            return Err(String::from("no code generated by Cretonne"));
+        }
+        let mut code_buf: Vec<u8> = vec![0; code_size];
+        let mut relocsink = StandaloneRelocSink::new();
+        let mut trapsink = StandaloneTrapSink::new();
+        context.emit_to_memory(code_buf.as_mut_ptr(), &mut relocsink, &mut trapsink, isa);
+        unsafe {
+            patch_ebb_relocs(code_buf.as_mut_ptr(), &relocsink, &context.func);
+        }
+
+        obj.declare(func_name(function_index), Decl::function().global())
+            .map_err(|e| e.to_string())?;
+        compiled.push((func_name(function_index), code_buf, relocsink, context.func));
+    }
+
+    // Declare the libcalls and host imports referenced via `reloc_external` as undefined
+    // symbols, so the linker resolves them against the host's libm/libc or the embedder's own
+    // import implementations.
+    for &(_, _, ref relocsink, _) in &compiled {
+        for &(ref name, _, _) in &relocsink.externals {
+            // Cretonne may reference the same libcall or import from several functions; faerie
+            // rejects re-declaring a symbol, so the result of `declare` is discarded here (which
+            // also discards any other declaration error).
+            let _ = obj.declare(external_symbol_name(name), Decl::function_import());
+        }
+    }
+
+    for (name, code_buf, relocsink, il_func) in compiled {
+        obj.define(&name, code_buf).map_err(|e| e.to_string())?;
+
+        for &(func_ref, offset) in &relocsink.funcs {
+            let target_index = runtime.func_indices[func_ref] - trans_result.function_imports_count;
+            obj.link(Link {
+                from: &name,
+                to: &func_name(target_index),
+                at: offset as u64,
+            }).map_err(|e| e.to_string())?;
+        }
+        for &(ref external_name, _, offset) in &relocsink.externals {
+            obj.link(Link {
+                from: &name,
+                to: &external_symbol_name(external_name),
+                at: offset as u64,
+            }).map_err(|e| e.to_string())?;
+        }
+        for &(jt, reloc, offset) in &relocsink.jts {
+            // The table can end up linked anywhere relative to the function, so its entries
+            // can't be baked in as function-relative constants the way `relocate`'s in-memory
+            // path can once it knows the final address; instead emit a zeroed entry per target
+            // EBB and let the linker fill it in as a relocation against the function symbol,
+            // with the EBB's offset into the function as the addend. This mirrors exactly the
+            // `entry_address - table_address`/absolute-pointer computation `relocate` performs.
+            let entry_size = jt_entry_size(reloc);
+            let table_bytes = vec![0u8; il_func.jump_tables[jt].len() * entry_size];
+            let table_symbol = format!("{}$jt{}", name, jt);
+            obj.declare(&table_symbol, Decl::data().global()).map_err(
+                |e| e.to_string(),
+            )?;
+            obj.define(&table_symbol, table_bytes).map_err(
+                |e| e.to_string(),
+            )?;
+            for (i, &ebb) in il_func.jump_tables[jt].iter().enumerate() {
+                let object_reloc = match reloc {
+                    Reloc::Abs8 => {
+                        ObjReloc::Raw {
+                            reloc: R_X86_64_64,
+                            addend: il_func.offsets[ebb] as i32,
+                        }
+                    }
+                    _ => {
+                        // R_X86_64_PC32's linked value is `S + A - P`, where `P` is the address
+                        // of this specific slot (`table_base + i*entry_size`), not the table's
+                        // base - so without canceling that out here, only slot `i == 0` would
+                        // come out as `entry_address - table_base` like `relocate`'s in-memory
+                        // path computes; every later arm would be off by `i*entry_size`.
+                        ObjReloc::Raw {
+                            reloc: R_X86_64_PC32,
+                            addend: il_func.offsets[ebb] as i32 +
+                                jt_entry_offset(entry_size, i) as i32,
+                        }
+                    }
+                };
+                obj.link_with(
+                    Link {
+                        from: &table_symbol,
+                        to: &name,
+                        at: jt_entry_offset(entry_size, i) as u64,
+                    },
+                    object_reloc,
+                ).map_err(|e| e.to_string())?;
+            }
+            obj.link(Link {
+                from: &name,
+                to: &table_symbol,
+                at: offset as u64,
+            }).map_err(|e| e.to_string())?;
+        }
+    }
+
+    obj.emit().map_err(|e| e.to_string())
+}
+
+/// Patches position-independent intra-function EBB branch relocations directly into `code`
+/// (the base address of a single function's body). These deltas only depend on offsets within
+/// the function, so they are the same regardless of where the function ultimately loads -
+/// shared between the in-memory JIT path and the object-file backend.
+unsafe fn patch_ebb_relocs(code: *mut u8, relocs: &StandaloneRelocSink, il_func: &Function) {
+    for &(ebb, offset) in &relocs.ebbs {
+        let reloc_address: isize = code.offset(offset as isize + 4) as isize;
+        let target_ebb_address: isize = code.offset(il_func.offsets[ebb] as isize) as isize;
+        let reloc_delta_i32: i32 = (target_ebb_address - reloc_address) as i32;
+        write_unaligned(reloc_address as *mut i32, reloc_delta_i32);
     }
 }
 
@@ -152,43 +889,103 @@ pub fn execute(exec: &ExecutableCode) -> Result<(), String> {
 fn relocate(
     function_imports_count: usize,
     functions_metatada: &[FunctionMetaData],
-    functions_code: &mut Vec<Vec<u8>>,
+    code: *mut u8,
+    offsets: &[usize],
     runtime: &StandaloneRuntime,
-) {
+    symbol_resolver: Option<&SymbolResolver>,
+) -> Result<(), String> {
     // The relocations are relative to the relocation's address plus four bytes
     for (func_index, function_in_memory) in functions_metatada.iter().enumerate() {
         let FunctionMetaData {
             ref relocs,
             ref il_func,
+            jt_base,
+            ref jt_layout,
         } = *function_in_memory;
-        for &(func_ref, offset) in relocs.funcs.values() {
+        let body_offset = offsets[func_index] as isize;
+        for &(func_ref, offset) in &relocs.funcs {
             let target_func_index = runtime.func_indices[func_ref] - function_imports_count;
-            let target_func_address: isize = functions_code[target_func_index].as_ptr() as isize;
             unsafe {
-                let reloc_address: isize = functions_code[func_index].as_mut_ptr().offset(
-                    offset as isize +
-                        4,
-                ) as isize;
+                let target_func_address: isize =
+                    code.offset(offsets[target_func_index] as isize) as isize;
+                let reloc_address: isize =
+                    code.offset(body_offset + offset as isize + 4) as isize;
                 let reloc_delta_i32: i32 = (target_func_address - reloc_address) as i32;
                 write_unaligned(reloc_address as *mut i32, reloc_delta_i32);
             }
         }
-        for &(ebb, offset) in relocs.ebbs.values() {
+        unsafe {
+            patch_ebb_relocs(code.offset(body_offset), relocs, il_func);
+        }
+        for &(jt, reloc, offset) in &relocs.jts {
+            let table_offset = jt_base + jt_layout[&jt];
             unsafe {
-                let reloc_address: isize = functions_code[func_index].as_mut_ptr().offset(
-                    offset as isize +
-                        4,
-                ) as isize;
-                let target_ebb_address: isize = functions_code[func_index].as_ptr().offset(
-                    il_func.offsets[ebb] as
-                        isize,
-                ) as isize;
-                let reloc_delta_i32: i32 = (target_ebb_address - reloc_address) as i32;
-                write_unaligned(reloc_address as *mut i32, reloc_delta_i32);
+                let table_address: isize = code.offset(table_offset as isize) as isize;
+                match reloc {
+                    Reloc::Abs8 => {
+                        // Entries are absolute 8-byte pointers to the target EBB.
+                        let entry_size = jt_entry_size(reloc);
+                        for (i, &ebb) in il_func.jump_tables[jt].iter().enumerate() {
+                            let entry_address: isize =
+                                code.offset(body_offset + il_func.offsets[ebb] as isize) as isize;
+                            let entry_slot = code.offset(
+                                table_offset as isize + jt_entry_offset(entry_size, i) as isize,
+                            );
+                            write_unaligned(entry_slot as *mut i64, entry_address as i64);
+                        }
+                        let reloc_address: isize =
+                            code.offset(body_offset + offset as isize) as isize;
+                        write_unaligned(reloc_address as *mut i64, table_address as i64);
+                    }
+                    _ => {
+                        // Entries are 32-bit offsets relative to the table's own base address,
+                        // matching how x86's `jump_table_base`/`jump_table_entry` lowering reads
+                        // them back, at the native 4-byte stride that lowering indexes with (not
+                        // the 8-byte stride the `Abs8` case above needs).
+                        let entry_size = jt_entry_size(reloc);
+                        for (i, &ebb) in il_func.jump_tables[jt].iter().enumerate() {
+                            let entry_address: isize =
+                                code.offset(body_offset + il_func.offsets[ebb] as isize) as isize;
+                            let entry_delta_i32: i32 = (entry_address - table_address) as i32;
+                            let entry_slot = code.offset(
+                                table_offset as isize + jt_entry_offset(entry_size, i) as isize,
+                            );
+                            write_unaligned(entry_slot as *mut i32, entry_delta_i32);
+                        }
+                        let reloc_address: isize =
+                            code.offset(body_offset + offset as isize + 4) as isize;
+                        let reloc_delta_i32: i32 = (table_address - reloc_address) as i32;
+                        write_unaligned(reloc_address as *mut i32, reloc_delta_i32);
+                    }
+                }
+            }
+        }
+        for &(ref name, reloc, offset) in &relocs.externals {
+            let target_address = resolve_external(name, symbol_resolver)
+                .ok_or_else(|| format!("unresolved external symbol: {}", name))?
+                as isize;
+            unsafe {
+                match reloc {
+                    Reloc::X86PCRel4 | Reloc::X86CallPCRel4 => {
+                        let reloc_address: isize =
+                            code.offset(body_offset + offset as isize + 4) as isize;
+                        let reloc_delta_i32: i32 = (target_address - reloc_address) as i32;
+                        write_unaligned(reloc_address as *mut i32, reloc_delta_i32);
+                    }
+                    Reloc::Abs4 => {
+                        let reloc_address: isize = code.offset(body_offset + offset as isize) as isize;
+                        write_unaligned(reloc_address as *mut i32, target_address as i32);
+                    }
+                    Reloc::Abs8 => {
+                        let reloc_address: isize = code.offset(body_offset + offset as isize) as isize;
+                        write_unaligned(reloc_address as *mut i64, target_address as i64);
+                    }
+                    other => panic!("unsupported external relocation kind: {:?}", other),
+                }
             }
         }
-        // TODO: deal with jumptable relocations
     }
+    Ok(())
 }
 
 /// Pretty-print a verifier error.
@@ -216,3 +1013,111 @@ pub fn pretty_error(func: &Function, isa: Option<&TargetIsa>, err: CtonError) ->
         err.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cretonne::ir::JumpTableData;
+    use std::str::FromStr;
+    use target_lexicon::Triple;
+
+    #[test]
+    fn jt_entry_size_matches_reloc_kind() {
+        // `Abs8` needs a full pointer-width slot; every other kind this module handles is the
+        // 4-byte table-relative offset x86's `jump_table_entry` lowering actually reads back.
+        assert_eq!(jt_entry_size(Reloc::Abs8), size_of::<u64>());
+        assert_eq!(jt_entry_size(Reloc::X86PCRel4), size_of::<u32>());
+        assert_eq!(jt_entry_size(Reloc::X86CallPCRel4), size_of::<u32>());
+    }
+
+    #[test]
+    fn dense_br_table_entries_land_at_distinct_slots() {
+        // Regression test for a dense `br_table` (more than one arm): laying entries out at a
+        // stride that doesn't match the width the reloc kind actually needs used to leave every
+        // arm but the first read from the wrong slot, since the 4-byte entries x86 emits were
+        // being spaced 8 bytes apart. Each arm must land at its own, correctly-sized slot with
+        // no gaps and no overlap.
+        let entry_size = jt_entry_size(Reloc::X86PCRel4);
+        let offsets: Vec<usize> = (0..8).map(|i| jt_entry_offset(entry_size, i)).collect();
+        assert_eq!(offsets, vec![0, 4, 8, 12, 16, 20, 24, 28]);
+
+        let entry_size = jt_entry_size(Reloc::Abs8);
+        let offsets: Vec<usize> = (0..8).map(|i| jt_entry_offset(entry_size, i)).collect();
+        assert_eq!(offsets, vec![0, 8, 16, 24, 32, 40, 48, 56]);
+    }
+
+    #[test]
+    fn dense_br_table_dispatches_to_the_right_arm() {
+        // End-to-end regression test for a dense `br_table`: builds a function that branches on
+        // its argument through an 8-arm jump table, compiles it through `compile_module` (the
+        // same path a real wasm `br_table` goes through), and checks that invoking it with every
+        // in-range index - and one out of range - actually lands on the right arm. This is the
+        // level the entry-size-stride and table-relative-addressing bugs in this area only
+        // showed up at; the pure arithmetic checks above wouldn't have caught either one.
+        const ARM_COUNT: usize = 8;
+
+        let mut shared_builder = settings::builder();
+        shared_builder.enable("enable_verifier").unwrap();
+        shared_builder.set("is_64bit", "1").unwrap();
+        let isa_builder = isa::lookup(Triple::from_str("x86_64").unwrap()).unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(&shared_builder));
+
+        let mut signature = Signature::new(isa.default_call_conv());
+        signature.params.push(AbiParam::new(types::I32));
+        signature.returns.push(AbiParam::new(types::I32));
+        let mut func = Function::with_name_signature(ExternalName::user(0, 0), signature);
+        let mut fn_builder_ctx = FunctionBuilderContext::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_builder_ctx);
+            let entry = builder.create_ebb();
+            let default_ebb = builder.create_ebb();
+            let arms: Vec<Ebb> = (0..ARM_COUNT).map(|_| builder.create_ebb()).collect();
+
+            builder.append_ebb_params_for_function_params(entry);
+            builder.switch_to_block(entry);
+            builder.seal_block(entry);
+            let index = builder.ebb_params(entry)[0];
+            let mut jt_data = JumpTableData::new();
+            for &arm in &arms {
+                jt_data.push_entry(arm);
+            }
+            let jt = builder.create_jump_table(jt_data);
+            builder.ins().br_table(index, default_ebb, jt);
+
+            for (i, &arm) in arms.iter().enumerate() {
+                builder.switch_to_block(arm);
+                builder.seal_block(arm);
+                let result = builder.ins().iconst(types::I32, i as i64);
+                builder.ins().return_(&[result]);
+            }
+
+            builder.switch_to_block(default_ebb);
+            builder.seal_block(default_ebb);
+            let result = builder.ins().iconst(types::I32, -1);
+            builder.ins().return_(&[result]);
+
+            builder.finalize();
+        }
+
+        let mut exports = HashMap::new();
+        exports.insert("dispatch".to_string(), 0);
+        let mut trans_result = TranslationResult {
+            functions: vec![func],
+            exports,
+            start_index: Some(0),
+            function_imports_count: 0,
+        };
+
+        let runtime = StandaloneRuntime::new();
+        let exec = compile_module(&mut trans_result, &*isa, &runtime, None).unwrap();
+
+        for i in 0..ARM_COUNT {
+            let result = exec.invoke(&*isa, "dispatch", &[Val::I32(i as i32)]).unwrap();
+            assert_eq!(result, vec![Val::I32(i as i32)]);
+        }
+        // An out-of-range index must fall through to the `br_table`'s default arm, not whatever
+        // happens to follow the last in-range entry in memory.
+        let result = exec.invoke(&*isa, "dispatch", &[Val::I32(ARM_COUNT as i32)]).unwrap();
+        assert_eq!(result, vec![Val::I32(-1)]);
+    }
+}